@@ -0,0 +1,342 @@
+/*
+ * Bytecode
+ * A textual, round-trippable core syntax for a compiled program: the
+ * entry `Func` plus the `VM` global/symbol tables it closes over, in the
+ * spirit of HVM's `ast.rs` pretty-printer/parser pair. `ck compile` writes
+ * this out as a `.ckb` file; `ck run` on a `.ckb` file loads it straight
+ * into a `VM` and skips re-parsing the original script.
+ *
+ * Grammar (s-expression style, `;` starts a line comment):
+ *   program  := (sym <id> <name>)* (glob <id> <value>)* (entry <func>)
+ *   value    := nil | (int N) | (float N) | (str "...") | (complex N N)
+ *             | (magic <name>) | (cons <value> <value>) | <func>
+ *   func     := (func <instr>*)
+ *   instr    := (load #<id>) | (app #<id>) | (set #<id>)
+ *
+ * Symbol and global tables are emitted in id order so a reload produces
+ * the exact same `SymbolId` assignment as the program that wrote them.
+ */
+
+use crate::diag::Span;
+use crate::{Func, Instr, InstrKind, Literal, SymbolId, Value, VM, PRELUDE};
+use std::rc::Rc;
+
+fn magic_name(m: crate::Magic) -> &'static str {
+    PRELUDE
+        .iter()
+        .find(|(_, mm)| std::mem::discriminant(mm) == std::mem::discriminant(&m))
+        .map(|(name, _)| *name)
+        .expect("every Magic variant is named in PRELUDE")
+}
+
+fn magic_by_name(name: &str) -> Option<crate::Magic> {
+    PRELUDE.iter().find(|(n, _)| *n == name).map(|(_, m)| *m)
+}
+
+fn escape_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Unescape a quoted token from [`tokenize`] (e.g. `"foo\nbar"`), rejecting
+/// anything that isn't properly `"`-delimited rather than silently keeping
+/// the stray quote characters.
+fn unescape_str(tok: &str) -> Result<String, String> {
+    let inner = tok
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("malformed string literal '{}'", tok))?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => {}
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    Ok(out)
+}
+
+/// The marker `dump` puts at the top of every file, and the only thing
+/// `load` checks before committing to parsing the rest as bytecode.
+const HEADER: &str = "; chicken-knife bytecode v1";
+
+/// Whether `text` looks like a file [`dump`] produced, as opposed to plain
+/// ck source — used by `ck run` to tell the two apart regardless of the
+/// file's name.
+pub fn looks_like_bytecode(text: &str) -> bool {
+    text.trim_start().starts_with(HEADER)
+}
+
+// Writer
+
+pub fn dump(vm: &VM, entry: &Func) -> String {
+    let mut out = String::new();
+    out.push_str(HEADER);
+    out.push('\n');
+
+    let mut names: Vec<Option<&str>> = vec![None; vm.sym_cnt as usize];
+    for (name, id) in &vm.sym_map {
+        names[*id as usize] = Some(name.as_str());
+    }
+    for (id, name) in names.into_iter().enumerate() {
+        if let Some(name) = name {
+            out.push_str(&format!("(sym {} {})\n", id, escape_str(name)));
+        }
+    }
+
+    for id in 0..vm.sym_cnt as usize {
+        out.push_str(&format!("(glob {} ", id));
+        write_value(&mut out, &vm.global[id]);
+        out.push_str(")\n");
+    }
+
+    out.push_str("(entry ");
+    write_func(&mut out, entry);
+    out.push_str(")\n");
+    out
+}
+
+fn write_value(out: &mut String, v: &Value) {
+    match v {
+        Value::Lit(Literal::Nil) => out.push_str("nil"),
+        Value::Lit(Literal::Int(n)) => out.push_str(&format!("(int {})", n)),
+        Value::Lit(Literal::Float(n)) => out.push_str(&format!("(float {})", n)),
+        Value::Lit(Literal::Str(s)) => out.push_str(&format!("(str {})", escape_str(s))),
+        Value::Lit(Literal::Complex(re, im)) => out.push_str(&format!("(complex {} {})", re, im)),
+        Value::Magic(m) => out.push_str(&format!("(magic {})", magic_name(*m))),
+        Value::Func(f) => write_func(out, f),
+        Value::Cons(a, b) => {
+            out.push_str("(cons ");
+            write_value(out, a);
+            out.push(' ');
+            write_value(out, b);
+            out.push(')');
+        }
+    }
+}
+
+fn write_func(out: &mut String, f: &Func) {
+    out.push_str("(func");
+    for instr in &f.instrs {
+        out.push(' ');
+        match instr.kind {
+            InstrKind::Load(id) => out.push_str(&format!("(load #{})", id)),
+            InstrKind::App(id) => out.push_str(&format!("(app #{})", id)),
+            InstrKind::Set(id) => out.push_str(&format!("(set #{})", id)),
+        }
+    }
+    out.push(')');
+}
+
+// Reader
+
+fn tokenize(s: &str) -> Vec<String> {
+    let mut toks = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == ';' {
+            for c in chars.by_ref() {
+                if c == '\n' {
+                    break;
+                }
+            }
+        } else if c == '(' || c == ')' {
+            toks.push(chars.next().unwrap().to_string());
+        } else if c == '"' {
+            let mut tok = String::new();
+            tok.push(chars.next().unwrap());
+            let mut escaped = false;
+            for c in chars.by_ref() {
+                tok.push(c);
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == '"' {
+                    break;
+                }
+            }
+            toks.push(tok);
+        } else {
+            let mut tok = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+                tok.push(c);
+                chars.next();
+            }
+            toks.push(tok);
+        }
+    }
+    toks
+}
+
+struct TokCursor {
+    toks: Vec<String>,
+    pos: usize,
+}
+
+impl TokCursor {
+    fn peek(&self) -> Option<&str> {
+        self.toks.get(self.pos).map(|s| s.as_str())
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let t = self.toks.get(self.pos).cloned();
+        if t.is_some() {
+            self.pos += 1;
+        }
+        t
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t == s => Ok(()),
+            other => Err(format!("expected '{}', found {:?}", s, other)),
+        }
+    }
+
+    fn next_or(&mut self, what: &str) -> Result<String, String> {
+        self.next().ok_or_else(|| format!("expected {}, found end of input", what))
+    }
+}
+
+/// Read a program written by [`dump`], repopulating `vm`'s symbol and
+/// global tables in place, and returning the entry point to run.
+pub fn load(vm: &mut VM, text: &str) -> Result<Func, String> {
+    if !looks_like_bytecode(text) {
+        return Err(format!("not a ck bytecode file (missing '{}' header)", HEADER));
+    }
+    let mut cur = TokCursor { toks: tokenize(text), pos: 0 };
+    vm.sym_map.clear();
+    vm.global.clear();
+    vm.sym_cnt = 0;
+    let mut entry = None;
+
+    while let Some(tok) = cur.peek() {
+        if tok != "(" {
+            return Err(format!("expected '(', found '{}'", tok));
+        }
+        cur.next();
+        let tag = cur.next_or("a form tag")?;
+        match tag.as_str() {
+            "sym" => {
+                let id = read_symbol_id(&mut cur)?;
+                let name = unescape_str(&cur.next_or("a symbol name")?)?;
+                vm.sym_map.insert(name, id);
+                vm.sym_cnt = vm.sym_cnt.max(id + 1);
+                cur.expect(")")?;
+            }
+            "glob" => {
+                let id = read_symbol_id(&mut cur)?;
+                let v = read_value(&mut cur)?;
+                while vm.global.len() <= id as usize {
+                    vm.global.push(Value::Lit(Literal::Nil));
+                }
+                vm.global[id as usize] = v;
+                vm.sym_cnt = vm.sym_cnt.max(id + 1);
+                cur.expect(")")?;
+            }
+            "entry" => {
+                entry = Some(read_func(&mut cur)?);
+                cur.expect(")")?;
+            }
+            other => return Err(format!("unknown top-level form '{}'", other)),
+        }
+    }
+
+    entry.ok_or_else(|| "missing (entry ...) form".to_string())
+}
+
+fn read_symbol_id(cur: &mut TokCursor) -> Result<SymbolId, String> {
+    cur.next_or("an id")?.parse().map_err(|_| "invalid id".to_string())
+}
+
+fn read_value(cur: &mut TokCursor) -> Result<Value, String> {
+    if cur.peek() == Some("nil") {
+        cur.next();
+        return Ok(Value::Lit(Literal::Nil));
+    }
+    cur.expect("(")?;
+    let tag = cur.next_or("a value tag")?;
+    let v = match tag.as_str() {
+        "int" => Value::Lit(Literal::Int(
+            cur.next_or("an int")?.parse().map_err(|_| "invalid int".to_string())?,
+        )),
+        "float" => Value::Lit(Literal::Float(
+            cur.next_or("a float")?.parse().map_err(|_| "invalid float".to_string())?,
+        )),
+        "str" => Value::Lit(Literal::Str(unescape_str(&cur.next_or("a string")?)?)),
+        "complex" => {
+            let re = cur.next_or("a real part")?.parse().map_err(|_| "invalid real part".to_string())?;
+            let im = cur.next_or("an imaginary part")?.parse().map_err(|_| "invalid imaginary part".to_string())?;
+            Value::Lit(Literal::Complex(re, im))
+        }
+        "magic" => {
+            let name = cur.next_or("a magic name")?;
+            Value::Magic(magic_by_name(&name).ok_or_else(|| format!("unknown magic '{}'", name))?)
+        }
+        "cons" => {
+            let a = read_value(cur)?;
+            let b = read_value(cur)?;
+            Value::Cons(Rc::new(a), Rc::new(b))
+        }
+        "func" => {
+            let mut instrs = Vec::new();
+            while cur.peek() != Some(")") {
+                instrs.push(read_instr(cur)?);
+            }
+            Value::Func(Func { instrs })
+        }
+        other => return Err(format!("unknown value tag '{}'", other)),
+    };
+    cur.expect(")")?;
+    Ok(v)
+}
+
+fn read_func(cur: &mut TokCursor) -> Result<Func, String> {
+    match read_value(cur)? {
+        Value::Func(f) => Ok(f),
+        _ => Err("expected a (func ...) form".to_string()),
+    }
+}
+
+fn read_instr(cur: &mut TokCursor) -> Result<Instr, String> {
+    cur.expect("(")?;
+    let tag = cur.next_or("an instruction tag")?;
+    let kind = match tag.as_str() {
+        "load" => InstrKind::Load(read_symref(cur)?),
+        "app" => InstrKind::App(read_symref(cur)?),
+        "set" => InstrKind::Set(read_symref(cur)?),
+        other => return Err(format!("unknown instruction '{}'", other)),
+    };
+    cur.expect(")")?;
+    // Spans don't survive the round trip: there's no original source text
+    // left to point a caret at.
+    Ok(Instr::new(kind, Span::new(0, 0)))
+}
+
+fn read_symref(cur: &mut TokCursor) -> Result<SymbolId, String> {
+    let tok = cur.next_or("'#<id>'")?;
+    tok.strip_prefix('#')
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| format!("invalid symbol reference '{}'", tok))
+}
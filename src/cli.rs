@@ -0,0 +1,42 @@
+/*
+ * CLI
+ * `ck`'s subcommands, declared declaratively with clap instead of the old
+ * hand-rolled `-i`/`-c`/`-f` argument loop.
+ */
+
+use clap::{Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(name = "ck", version, about = "A simple & light-weight stack-based text processor")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run a script file, or a `.ckb` file produced by `compile`
+    Run {
+        file: String,
+        /// Buffer file, opened and read at startup; its contents are not stored or used
+        #[arg(short = 'f', long)]
+        buffer: Option<String>,
+    },
+    /// Parse a file and report diagnostics, without executing it
+    Check { file: String },
+    /// Compile a script to ck's textual bytecode format for fast re-loading
+    Compile {
+        file: String,
+        /// Where to write the compiled `.ckb` file
+        #[arg(short = 'o', long)]
+        output: String,
+    },
+    /// Start the interactive REPL
+    Repl {
+        /// Buffer file, opened and read at startup; its contents are not stored or used
+        #[arg(short = 'f', long)]
+        buffer: Option<String>,
+    },
+    /// Evaluate an inline snippet of code
+    Eval { code: String },
+}
@@ -0,0 +1,81 @@
+/*
+ * Diagnostics
+ * Source-span tracking and caret-underlined error rendering, shared by the
+ * parser and the VM.
+ */
+
+/// A half-open byte range `[start, end)` into a source string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+/// A parse or runtime error anchored to a span of the original source.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Self { span, message: message.into() }
+    }
+
+    /// Render as `error: <message>` followed by the offending source line
+    /// and a `^^^` underline beneath the span, e.g.:
+    ///
+    /// ```text
+    /// error: unmatched ')'
+    ///  --> 2:8
+    ///   |
+    /// 2 | (+ 1 2))
+    ///   |        ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let (line_no, col_no, line) = locate(source, self.span.start);
+        let width = self.span.end.saturating_sub(self.span.start).max(1);
+        let gutter = format!("{}", line_no).len();
+        let mut out = String::new();
+        out.push_str(&format!("error: {}\n", self.message));
+        out.push_str(&format!("{}--> {}:{}\n", " ".repeat(gutter), line_no, col_no));
+        out.push_str(&format!("{} |\n", " ".repeat(gutter)));
+        out.push_str(&format!("{} | {}\n", line_no, line));
+        out.push_str(&format!(
+            "{} | {}{}\n",
+            " ".repeat(gutter),
+            " ".repeat(col_no.saturating_sub(1)),
+            "^".repeat(width)
+        ));
+        out
+    }
+}
+
+/// Locate the 1-based `(line, column)` of byte offset `pos` in `source`,
+/// along with the full text of that line (for the underline to sit under).
+fn locate(source: &str, pos: usize) -> (usize, usize, &str) {
+    let mut line_no = 1;
+    let mut line_start = 0;
+    for (i, c) in source.char_indices() {
+        if i >= pos {
+            break;
+        }
+        if c == '\n' {
+            line_no += 1;
+            line_start = i + 1;
+        }
+    }
+    let line_end = source[line_start..]
+        .find('\n')
+        .map(|o| line_start + o)
+        .unwrap_or(source.len());
+    let col_no = source[line_start..pos].chars().count() + 1;
+    (line_no, col_no, &source[line_start..line_end])
+}
@@ -10,45 +10,17 @@
  *
  */
 
-use ctrlc;
-use indoc::indoc;
-use std::{char, collections::HashMap, fs::File, io::Read, iter::Peekable, process::exit, rc::Rc, str::Chars};
-use std::io::{Write};
+use clap::Parser as ClapParser;
+use rustyline::error::ReadlineError;
+use std::{char, collections::HashMap, fs::File, io::Read, iter::Peekable, path::PathBuf, process::exit, rc::Rc, str::Chars};
 
-// Based on ck.c
+mod bytecode;
+mod cli;
+mod diag;
+use cli::{Cli, Command};
+use diag::{Diagnostic, Span};
 
-fn print_help_and_exit() {
-    println!(
-        indoc! {"
-            {} {}
-            A simple & light-weight stack-based text processor
-
-            Usage:
-            ck [options] [script files]
-
-            Options:
-            -i       \tInteractive Mode
-            -c <code>\tInline code
-            -f <file>\tFile for initial buffer content
-            -h       \tPrint this help message and exit
-            -v       \tPrint version and exit
-        "},
-        env!("CARGO_PKG_VERSION"),
-        env!("CARGO_PKG_NAME")
-    );
-    exit(0);
-}
-
-fn print_version_and_exit() {
-    println!(
-        indoc! {r#"
-        {} {}
-    "#},
-        env!("CARGO_PKG_NAME"),
-        env!("CARGO_PKG_VERSION")
-    );
-    exit(0);
-}
+// Based on ck.c
 
 // Symbol Table
 
@@ -56,15 +28,36 @@ type SymbolId = u32;
 
 // Value
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum Literal {
     Nil,
     Int(i64),
     Float(f64),
     Str(String),
+    Complex(f64, f64), // re, im
+}
+
+impl std::fmt::Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Literal::Nil => write!(f, "nil"),
+            Literal::Int(n) => write!(f, "{}", n),
+            Literal::Float(n) => write!(f, "{}", n),
+            Literal::Str(s) => write!(f, "{}", s),
+            Literal::Complex(re, im) => {
+                if *im == 0.0 {
+                    write!(f, "{}", re)
+                } else if *im < 0.0 {
+                    write!(f, "{}-{}i", re, -im)
+                } else {
+                    write!(f, "{}+{}i", re, im)
+                }
+            }
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum Value {
     Lit(Literal),
     Cons(Rc<Value>, Rc<Value>),
@@ -72,19 +65,204 @@ enum Value {
     Func(Func),
 }
 
-#[derive(Debug)]
-enum Instr {
+// Numeric tower: Int promotes to Float promotes to Complex. Shared by the
+// arithmetic/comparison `Magic` ops.
+
+#[derive(Clone, Copy)]
+enum Num {
+    Int(i64),
+    Float(f64),
+    Complex(f64, f64),
+}
+
+impl Num {
+    fn from_value(v: &Value) -> Option<Num> {
+        match v {
+            Value::Lit(Literal::Int(n)) => Some(Num::Int(*n)),
+            Value::Lit(Literal::Float(n)) => Some(Num::Float(*n)),
+            Value::Lit(Literal::Complex(re, im)) => Some(Num::Complex(*re, *im)),
+            _ => None,
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Num::Int(_) => 0,
+            Num::Float(_) => 1,
+            Num::Complex(..) => 2,
+        }
+    }
+
+    fn as_f64(&self) -> f64 {
+        match self {
+            Num::Int(n) => *n as f64,
+            Num::Float(n) => *n,
+            Num::Complex(re, _) => *re,
+        }
+    }
+
+    fn as_complex(&self) -> (f64, f64) {
+        match self {
+            Num::Int(n) => (*n as f64, 0.0),
+            Num::Float(n) => (*n, 0.0),
+            Num::Complex(re, im) => (*re, *im),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Num::Int(n) => Value::Lit(Literal::Int(n)),
+            Num::Float(n) => Value::Lit(Literal::Float(n)),
+            Num::Complex(re, im) => Value::Lit(Literal::Complex(re, im)),
+        }
+    }
+}
+
+fn as_nums(op: &str, a: &Value, b: &Value) -> Result<(Num, Num), String> {
+    match (Num::from_value(a), Num::from_value(b)) {
+        (Some(a), Some(b)) => Ok((a, b)),
+        _ => Err(format!("'{}' expects numeric operands", op)),
+    }
+}
+
+/// `a OP b`, promoting both operands to the higher of their two numeric
+/// kinds (Int < Float < Complex) before applying the matching closure.
+/// `int_op` is fallible so divide can reject a zero divisor instead of
+/// panicking.
+fn binary_arith(
+    op: &str,
+    a: &Value,
+    b: &Value,
+    int_op: impl Fn(i64, i64) -> Result<i64, String>,
+    float_op: impl Fn(f64, f64) -> f64,
+    complex_op: impl Fn((f64, f64), (f64, f64)) -> (f64, f64),
+) -> Result<Value, String> {
+    let (a, b) = as_nums(op, a, b)?;
+    Ok(match a.rank().max(b.rank()) {
+        0 => {
+            let (Num::Int(x), Num::Int(y)) = (a, b) else { unreachable!() };
+            Num::Int(int_op(x, y)?).into_value()
+        }
+        1 => Num::Float(float_op(a.as_f64(), b.as_f64())).into_value(),
+        _ => {
+            let (re, im) = complex_op(a.as_complex(), b.as_complex());
+            Num::Complex(re, im).into_value()
+        }
+    })
+}
+
+fn complex_mul((ar, ai): (f64, f64), (br, bi): (f64, f64)) -> (f64, f64) {
+    (ar * br - ai * bi, ar * bi + ai * br)
+}
+
+fn complex_div((ar, ai): (f64, f64), (br, bi): (f64, f64)) -> (f64, f64) {
+    // Multiply by the conjugate of the denominator over |b|^2.
+    let denom = br * br + bi * bi;
+    ((ar * br + ai * bi) / denom, (ai * br - ar * bi) / denom)
+}
+
+impl Value {
+    fn add(&self, other: &Value) -> Result<Value, String> {
+        binary_arith("+", self, other, |a, b| Ok(a + b), |a, b| a + b, |(ar, ai), (br, bi)| (ar + br, ai + bi))
+    }
+
+    fn sub(&self, other: &Value) -> Result<Value, String> {
+        binary_arith("-", self, other, |a, b| Ok(a - b), |a, b| a - b, |(ar, ai), (br, bi)| (ar - br, ai - bi))
+    }
+
+    fn mul(&self, other: &Value) -> Result<Value, String> {
+        binary_arith("*", self, other, |a, b| Ok(a * b), |a, b| a * b, complex_mul)
+    }
+
+    fn div(&self, other: &Value) -> Result<Value, String> {
+        binary_arith(
+            "/",
+            self,
+            other,
+            |a, b| a.checked_div(b).ok_or_else(|| "'/' by zero".to_string()),
+            |a, b| a / b,
+            complex_div,
+        )
+    }
+
+    /// Modulo is not defined for complex numbers.
+    fn rem(&self, other: &Value) -> Result<Value, String> {
+        let (a, b) = as_nums("%", self, other)?;
+        if a.rank() == 2 || b.rank() == 2 {
+            return Err("'%' is not defined for complex numbers".to_string());
+        }
+        Ok(match (a, b) {
+            (Num::Int(x), Num::Int(y)) => {
+                Value::Lit(Literal::Int(x.checked_rem(y).ok_or_else(|| "'%' by zero".to_string())?))
+            }
+            _ => Value::Lit(Literal::Float(a.as_f64() % b.as_f64())),
+        })
+    }
+
+    fn neg(&self) -> Result<Value, String> {
+        match Num::from_value(self) {
+            Some(Num::Int(n)) => Ok(Value::Lit(Literal::Int(-n))),
+            Some(Num::Float(n)) => Ok(Value::Lit(Literal::Float(-n))),
+            Some(Num::Complex(re, im)) => Ok(Value::Lit(Literal::Complex(-re, -im))),
+            None => Err("'neg' expects a numeric operand".to_string()),
+        }
+    }
+
+    /// Component-wise equality, promoting like `add`/`sub`/etc.
+    fn num_eq(&self, other: &Value) -> Result<bool, String> {
+        let (a, b) = as_nums("=", self, other)?;
+        Ok(match a.rank().max(b.rank()) {
+            0 => {
+                let (Num::Int(x), Num::Int(y)) = (a, b) else { unreachable!() };
+                x == y
+            }
+            1 => a.as_f64() == b.as_f64(),
+            _ => a.as_complex() == b.as_complex(),
+        })
+    }
+
+    /// Total ordering; errors if either operand is `Complex`, which has no
+    /// natural ordering.
+    fn num_cmp(&self, other: &Value, op: &str) -> Result<std::cmp::Ordering, String> {
+        let (a, b) = as_nums(op, self, other)?;
+        if a.rank() == 2 || b.rank() == 2 {
+            return Err(format!("'{}' is not defined for complex numbers", op));
+        }
+        match (a, b) {
+            (Num::Int(x), Num::Int(y)) => Ok(x.cmp(&y)),
+            _ => a
+                .as_f64()
+                .partial_cmp(&b.as_f64())
+                .ok_or_else(|| format!("'{}' produced an unordered (NaN) comparison", op)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum InstrKind {
     Load(SymbolId), // Load from global table
     App(SymbolId),  // Load function from global table and apply
     Set(SymbolId),  // Pop and set to global table
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
+struct Instr {
+    kind: InstrKind,
+    span: Span, // Where this instruction came from, for error reporting
+}
+
+impl Instr {
+    fn new(kind: InstrKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, Clone)]
 struct Func {
     instrs: Vec<Instr>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum Magic {
     Add,
     Sub,
@@ -110,6 +288,61 @@ enum Magic {
 
 // VM: Stack Machine
 
+/// One level of `App`-into-a-`Func` nesting: the callee's instructions
+/// (cloned out of `global` so the table can still be mutated while it
+/// runs) plus how far into them execution has gotten.
+struct Frame {
+    instrs: Vec<Instr>,
+    pc: usize,
+}
+
+/// Every builtin operator `seed_prelude` binds, alongside its source name.
+const PRELUDE: &[(&str, Magic)] = &[
+    ("+", Magic::Add),
+    ("-", Magic::Sub),
+    ("*", Magic::Mul),
+    ("/", Magic::Div),
+    ("%", Magic::Mod),
+    ("=", Magic::Eq),
+    ("!=", Magic::Neq),
+    ("<", Magic::Lt),
+    (">", Magic::Gt),
+    ("<=", Magic::Leq),
+    (">=", Magic::Geq),
+    ("and", Magic::And),
+    ("or", Magic::Or),
+    ("not", Magic::Not),
+    ("neg", Magic::Neg),
+    ("print", Magic::Print),
+    ("println", Magic::Println),
+    ("read", Magic::Read),
+    ("readln", Magic::Readln),
+    ("exit", Magic::Exit),
+];
+
+fn truthy(v: &Value) -> bool {
+    match v {
+        Value::Lit(Literal::Nil) => false,
+        Value::Lit(Literal::Int(n)) => *n != 0,
+        Value::Lit(Literal::Float(n)) => *n != 0.0,
+        Value::Lit(Literal::Complex(re, im)) => *re != 0.0 || *im != 0.0,
+        _ => true,
+    }
+}
+
+fn bool_to_value(b: bool) -> Value {
+    Value::Lit(Literal::Int(if b { 1 } else { 0 }))
+}
+
+fn display_value(v: &Value) -> String {
+    match v {
+        Value::Lit(l) => format!("{}", l),
+        Value::Cons(a, b) => format!("({} . {})", display_value(a), display_value(b)),
+        Value::Magic(_) => "<magic>".to_string(),
+        Value::Func(_) => "<func>".to_string(),
+    }
+}
+
 struct VM {
     // Symbol table
     sym_cnt: SymbolId,
@@ -119,17 +352,29 @@ struct VM {
     // Global table
     global: Vec<Value>,
     // Call Frame
-    call_frame: Vec<Value>,
+    call_frame: Vec<Frame>,
 }
 
 impl VM {
     fn new() -> Self {
-        Self {
+        let mut vm = Self {
             sym_cnt: 0,
             sym_map: HashMap::new(),
             stack: Vec::new(),
             global: Vec::new(),
             call_frame: Vec::new(),
+        };
+        vm.seed_prelude();
+        vm
+    }
+
+    /// Bind every builtin operator name to its `Magic` value in the global
+    /// table, so plain symbol lookup (`Instr::App`) finds it like any
+    /// user-defined function.
+    fn seed_prelude(&mut self) {
+        for (name, magic) in PRELUDE {
+            let id = self.get_id(name);
+            self.global[id as usize] = Value::Magic(*magic);
         }
     }
 
@@ -167,15 +412,150 @@ impl VM {
 		Ok(())
 	}
 
-    fn run(&mut self) {}
+    fn pop(&mut self) -> Result<Value, String> {
+        self.stack.pop().ok_or_else(|| "stack underflow".to_string())
+    }
+
+    /// Pop the operands a `Magic` op needs and push its result, in
+    /// evaluation order (the first-pushed operand is popped last).
+    fn apply_magic(&mut self, magic: Magic) -> Result<(), String> {
+        match magic {
+            Magic::Add => { let b = self.pop()?; let a = self.pop()?; self.stack.push(a.add(&b)?); }
+            Magic::Sub => { let b = self.pop()?; let a = self.pop()?; self.stack.push(a.sub(&b)?); }
+            Magic::Mul => { let b = self.pop()?; let a = self.pop()?; self.stack.push(a.mul(&b)?); }
+            Magic::Div => { let b = self.pop()?; let a = self.pop()?; self.stack.push(a.div(&b)?); }
+            Magic::Mod => { let b = self.pop()?; let a = self.pop()?; self.stack.push(a.rem(&b)?); }
+            Magic::Eq => { let b = self.pop()?; let a = self.pop()?; self.stack.push(bool_to_value(a.num_eq(&b)?)); }
+            Magic::Neq => { let b = self.pop()?; let a = self.pop()?; self.stack.push(bool_to_value(!a.num_eq(&b)?)); }
+            Magic::Lt => { let b = self.pop()?; let a = self.pop()?; self.stack.push(bool_to_value(a.num_cmp(&b, "<")?.is_lt())); }
+            Magic::Gt => { let b = self.pop()?; let a = self.pop()?; self.stack.push(bool_to_value(a.num_cmp(&b, ">")?.is_gt())); }
+            Magic::Leq => { let b = self.pop()?; let a = self.pop()?; self.stack.push(bool_to_value(a.num_cmp(&b, "<=")?.is_le())); }
+            Magic::Geq => { let b = self.pop()?; let a = self.pop()?; self.stack.push(bool_to_value(a.num_cmp(&b, ">=")?.is_ge())); }
+            Magic::And => { let b = self.pop()?; let a = self.pop()?; self.stack.push(bool_to_value(truthy(&a) && truthy(&b))); }
+            Magic::Or => { let b = self.pop()?; let a = self.pop()?; self.stack.push(bool_to_value(truthy(&a) || truthy(&b))); }
+            Magic::Not => { let a = self.pop()?; self.stack.push(bool_to_value(!truthy(&a))); }
+            Magic::Neg => { let a = self.pop()?; self.stack.push(a.neg()?); }
+            Magic::Print => { let a = self.pop()?; print!("{}", display_value(&a)); }
+            Magic::Println => { let a = self.pop()?; println!("{}", display_value(&a)); }
+            Magic::Read => {
+                let mut s = String::new();
+                std::io::stdin().read_line(&mut s).map_err(|e| e.to_string())?;
+                self.stack.push(Value::Lit(Literal::Str(s.trim().to_string())));
+            }
+            Magic::Readln => {
+                let mut s = String::new();
+                std::io::stdin().read_line(&mut s).map_err(|e| e.to_string())?;
+                self.stack.push(Value::Lit(Literal::Str(s.trim_end_matches(['\n', '\r']).to_string())));
+            }
+            Magic::Exit => {
+                let code = match self.stack.pop() {
+                    Some(Value::Lit(Literal::Int(n))) => n as i32,
+                    _ => 0,
+                };
+                exit(code);
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute `f` to completion: a simple fetch-decode-execute loop over
+    /// nested call frames, stopping at the first runtime error.
+    fn run(&mut self, f: &Func) -> Result<(), Diagnostic> {
+        self.call_frame.clear();
+        self.call_frame.push(Frame { instrs: f.instrs.clone(), pc: 0 });
+
+        while let Some(frame) = self.call_frame.last_mut() {
+            if frame.pc >= frame.instrs.len() {
+                self.call_frame.pop();
+                continue;
+            }
+            let instr = frame.instrs[frame.pc];
+            frame.pc += 1;
+
+            let result = match instr.kind {
+                InstrKind::Load(id) => {
+                    self.stack.push(self.global[id as usize].clone());
+                    Ok(())
+                }
+                InstrKind::Set(id) => match self.stack.pop() {
+                    Some(v) => {
+                        self.global[id as usize] = v;
+                        Ok(())
+                    }
+                    None => Err("stack underflow in 'set'".to_string()),
+                },
+                InstrKind::App(id) => match self.global[id as usize].clone() {
+                    Value::Magic(m) => self.apply_magic(m),
+                    Value::Func(callee) => {
+                        self.call_frame.push(Frame { instrs: callee.instrs, pc: 0 });
+                        Ok(())
+                    }
+                    _ => Err("value is not callable".to_string()),
+                },
+            };
+
+            if let Err(msg) = result {
+                self.call_frame.clear();
+                return Err(Diagnostic::new(instr.span, msg));
+            }
+        }
+        Ok(())
+    }
 }
 
 // Parse
 
+/// A character iterator that tracks its running byte offset into the
+/// source, so every token/instruction can be tagged with a `Span`.
+#[derive(Clone)]
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(s: &'a str) -> Self {
+        Self { chars: s.chars().peekable(), pos: 0 }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if let Some(c) = c {
+            self.pos += c.len_utf8();
+        }
+        c
+    }
+}
+
+/// Outcome of a failed parse: either the input simply ran out mid-token
+/// (REPL should ask for another line) or the input is outright malformed.
+/// Both carry a `Diagnostic` so a caller with no more input to offer (e.g.
+/// end of a file) can report the incomplete case as a hard error too.
+enum ParseErr {
+    Incomplete(Diagnostic),
+    Error(Diagnostic),
+}
+
+impl ParseErr {
+    fn diagnostic(self) -> Diagnostic {
+        match self {
+            ParseErr::Incomplete(d) => d,
+            ParseErr::Error(d) => d,
+        }
+    }
+}
+
+type PResult<T> = Result<T, ParseErr>;
+
 struct Parser<'vm> {
-    vm: &'vm mut VM,            // Destination VM
-    partial: String,            // Partial code
-    partial_f: Vec<Vec<Instr>>, // Partial function
+    vm: &'vm mut VM,                    // Destination VM
+    partial: String,                    // Partial code
+    partial_f: Vec<(Vec<Instr>, Span)>, // Partial function, with the span of its opening '('
+    last_source: String,                // Full text of the last parse_all call, for rendering spans after partial is truncated
 }
 
 fn is_special_char(c: char) -> bool {
@@ -185,7 +565,7 @@ fn is_special_char(c: char) -> bool {
     }
 }
 
-fn skip_whitespace(chars: &mut Peekable<Chars>) {
+fn skip_whitespace(chars: &mut Cursor) {
 	while let Some(c) = chars.peek() {
 		if !c.is_whitespace() {
 			break;
@@ -194,12 +574,49 @@ fn skip_whitespace(chars: &mut Peekable<Chars>) {
 	}
 }
 
-fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, Option<String>> {
+/// Parse a complex-number literal such as `4i`, `3+4i` or `2.5-1e3i`: an
+/// optional real part, then an imaginary part ending in `i`. Tried only
+/// after plain `i64`/`f64` parsing has already failed on the full token.
+fn parse_complex_literal(s: &str) -> Option<(f64, f64)> {
+    if !s.ends_with('i') || s.len() < 2 {
+        return None;
+    }
+    let body = &s[..s.len() - 1];
+    let bytes = body.as_bytes();
+    // Find the +/- that separates the real and imaginary parts, scanning
+    // from the right so we don't trip over a leading sign or one that's
+    // part of an exponent (`1e-3`).
+    let split = (1..bytes.len()).rev().find(|&i| {
+        let c = bytes[i] as char;
+        (c == '+' || c == '-') && bytes[i - 1] as char != 'e' && bytes[i - 1] as char != 'E'
+    });
+    let parse_signed_coefficient = |s: &str| -> Option<f64> {
+        match s {
+            "" | "+" => Some(1.0),
+            "-" => Some(-1.0),
+            _ => s.parse::<f64>().ok(),
+        }
+    };
+    match split {
+        Some(i) => {
+            let re = body[..i].parse::<f64>().ok()?;
+            let im = parse_signed_coefficient(&body[i..])?;
+            Some((re, im))
+        }
+        None => {
+            let im = parse_signed_coefficient(body)?;
+            Some((0.0, im))
+        }
+    }
+}
+
+fn parse_string(chars: &mut Cursor) -> PResult<(String, Span)> {
     // Check open
+    let start = chars.pos;
     let open = chars.next().unwrap();
     let mut open_n = 1;
     while let Some(c) = chars.peek() {
-        if *c != open {
+        if c != open {
             break;
         }
         open_n += 1;
@@ -212,14 +629,14 @@ fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, Option<String>> {
             // Check close
             let mut close_n = 1;
             while let Some(c) = chars.peek() {
-                if *c != open {
+                if c != open {
                     break;
                 }
                 close_n += 1;
                 chars.next();
             }
             if close_n == open_n {
-                return Ok(s);
+                return Ok((s, Span::new(start, chars.pos)));
             }
             // Otherwise, push open
             if open_n == 1 {
@@ -232,7 +649,10 @@ fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, Option<String>> {
             s.push(c);
         }
     }
-    Err(None)
+    Err(ParseErr::Incomplete(Diagnostic::new(
+        Span::new(start, start + open_n),
+        "unterminated string",
+    )))
 }
 
 impl Parser<'_> {
@@ -241,86 +661,112 @@ impl Parser<'_> {
             vm,
             partial: String::new(),
             partial_f: Vec::new(),
+            last_source: String::new(),
         }
     }
 
-    fn parse_all(&mut self) -> Result<Func, Option<String>> {
-        let mut chars = self.partial.chars().peekable();
+    /// The source text the spans of the last (possibly failed) parse are
+    /// relative to — use this to render a `Diagnostic`. A successful parse
+    /// truncates `partial` down to the unconsumed remainder, so this is
+    /// tracked separately rather than returning `&self.partial` directly.
+    fn source(&self) -> &str {
+        &self.last_source
+    }
+
+    fn parse_all(&mut self) -> PResult<Func> {
+        self.last_source = self.partial.clone();
+        // `self.partial` is re-tokenized from byte 0 on every call (it may
+        // carry text left over from a previous `Incomplete` attempt), so any
+        // '('-depth recorded by that previous attempt must be rebuilt from
+        // scratch here too, not accumulated on top of it.
+        self.partial_f.clear();
+        let mut chars = Cursor::new(&self.partial);
         let mut f = Vec::new();
         loop {
             skip_whitespace(&mut chars);
             if let Some(c) = chars.peek() {
-                match *c {
+                match c {
                     // Comment
                     '#' => {
                         // Skip until newline
-                        chars.find(|c| *c == '\n');
+                        while let Some(c) = chars.next() {
+                            if c == '\n' {
+                                break;
+                            }
+                        }
                     }
                     // String
                     '\'' | '"' | '`' => {
-                        // Save position
-                        let mut nchars = chars.clone();
-                        let s = parse_string(&mut nchars)?;
-                        println!("str: '{}'", s);
+                        let (s, span) = parse_string(&mut chars)?;
                         // Push string into global
                         let id = self.vm.alloc_id();
                         self.vm.global[id as usize] = Value::Lit(Literal::Str(s));
-                        f.push(Instr::Load(id));
-                        // Skip
-                        chars = nchars;
+                        f.push(Instr::new(InstrKind::Load(id), span));
                     }
                     '(' => {
                         // Parse function
-                        self.partial_f.push(f);
+                        let span = Span::new(chars.pos, chars.pos + 1);
+                        chars.next();
+                        self.partial_f.push((f, span));
                         f = Vec::new();
                     }
                     ')' => {
+                        let close = chars.pos;
+                        chars.next();
                         // End of function
-                        // Pack function
+                        let (parent_f, open_span) = self.partial_f.pop().ok_or_else(|| {
+                            ParseErr::Error(Diagnostic::new(
+                                Span::new(close, close + 1),
+                                "unmatched ')'",
+                            ))
+                        })?;
                         let func = Func { instrs: f };
-                        // Pop function
-                        f = self
-                            .partial_f
-                            .pop()
-                            .unwrap_or_else(|| panic!("ParsingError: Unexpected ')'"));
+                        f = parent_f;
                         let id = self.vm.alloc_id();
                         self.vm.global[id as usize] = Value::Func(func);
-                        f.push(Instr::Load(id));
+                        f.push(Instr::new(
+                            InstrKind::Load(id),
+                            Span::new(open_span.start, close + 1),
+                        ));
                     }
                     _ => {
                         // Otherwise, gather until special character
+                        let start = chars.pos;
                         let mut s = String::new();
                         while let Some(c) = chars.peek() {
-                            if is_special_char(*c) {
+                            if is_special_char(c) {
                                 break;
                             }
                             s.push(chars.next().unwrap());
                         }
-                        println!("id: '{}'", s);
-                        if s.starts_with("$=") {
+                        let span = Span::new(start, chars.pos);
+                        if let Some(name) = s.strip_prefix("$=") {
                             // Set global
-                            let id = self.vm.get_id(&s[2..]);
-                            f.push(Instr::Set(id));
-                        } else if s.starts_with("$") {
+                            let id = self.vm.get_id(name);
+                            f.push(Instr::new(InstrKind::Set(id), span));
+                        } else if let Some(name) = s.strip_prefix('$') {
                             // Load global
-                            let id = self.vm.get_id(&s[1..]);
-                            f.push(Instr::Load(id));
+                            let id = self.vm.get_id(name);
+                            f.push(Instr::new(InstrKind::Load(id), span));
                         } else if let Ok(n) = s.parse::<i64>() {
                             // Push number into global
                             let id = self.vm.alloc_id();
                             self.vm.global[id as usize] = Value::Lit(Literal::Int(n));
-                            println!("int: {}", n);
-                            f.push(Instr::Load(id));
+                            f.push(Instr::new(InstrKind::Load(id), span));
                         } else if let Ok(n) = s.parse::<f64>() {
                             // Push number into global
                             let id = self.vm.alloc_id();
                             self.vm.global[id as usize] = Value::Lit(Literal::Float(n));
-                            println!("float: {}", n);
-                            f.push(Instr::Load(id));
+                            f.push(Instr::new(InstrKind::Load(id), span));
+                        } else if let Some((re, im)) = parse_complex_literal(&s) {
+                            // Push complex number into global
+                            let id = self.vm.alloc_id();
+                            self.vm.global[id as usize] = Value::Lit(Literal::Complex(re, im));
+                            f.push(Instr::new(InstrKind::Load(id), span));
                         } else {
                             // Push symbol into global
                             let id = self.vm.get_id(&s);
-                            f.push(Instr::App(id));
+                            f.push(Instr::new(InstrKind::App(id), span));
                         }
                     }
                 }
@@ -328,90 +774,224 @@ impl Parser<'_> {
                 break;
             }
         }
-        self.partial = chars.collect();
+        if let Some((_, open_span)) = self.partial_f.last() {
+            return Err(ParseErr::Incomplete(Diagnostic::new(*open_span, "unclosed '('")));
+        }
+        self.partial = self.partial[chars.pos..].to_string();
         Ok(Func { instrs: f })
     }
 
-    fn parse(&mut self, code: &str) -> Result<Func, Option<String>> {
+    fn parse(&mut self, code: &str) -> PResult<Func> {
         // Create new string from partial code and code
         self.partial.push_str(code);
-        println!("code: '{}'", self.partial);
         self.parse_all()
     }
 }
 
 // Parsing args and run
 
-fn execute_code(vm: &mut VM, filename: String, code: String) {
+fn execute_code(vm: &mut VM, filename: String, code: String) -> bool {
 	let mut parser = Parser::new(vm);
-	parser.parse(&code);
-	vm.run();
+	match parser.parse(&code) {
+		Ok(f) => {
+			let source = parser.source().to_string();
+			drop(parser); // release the borrow of `vm` before running it
+			match vm.run(&f) {
+				Ok(()) => true,
+				Err(d) => {
+					eprintln!("{}: {}", filename, d.render(&source));
+					false
+				}
+			}
+		}
+		Err(e) => {
+			eprintln!("{}: {}", filename, e.diagnostic().render(parser.source()));
+			false
+		}
+	}
+}
+
+/// Parse `code` without executing it, reporting any diagnostic. Used by
+/// `ck check` to validate a script's syntax.
+fn check_code(vm: &mut VM, filename: String, code: String) -> bool {
+	let mut parser = Parser::new(vm);
+	match parser.parse(&code) {
+		Ok(_f) => true,
+		Err(e) => {
+			eprintln!("{}: {}", filename, e.diagnostic().render(parser.source()));
+			false
+		}
+	}
+}
+
+/// Parse `code`, then serialize the resulting `Func` and `vm`'s tables to
+/// `output` in the textual bytecode format, without executing anything.
+fn compile_code(vm: &mut VM, filename: String, code: String, output: &str) -> bool {
+	let mut parser = Parser::new(vm);
+	match parser.parse(&code) {
+		Ok(f) => {
+			drop(parser); // release the borrow of `vm` before reading its tables
+			let text = bytecode::dump(vm, &f);
+			match std::fs::write(output, text) {
+				Ok(()) => true,
+				Err(e) => {
+					eprintln!("Failed to write {}: {}", output, e);
+					false
+				}
+			}
+		}
+		Err(e) => {
+			eprintln!("{}: {}", filename, e.diagnostic().render(parser.source()));
+			false
+		}
+	}
+}
+
+/// Load bytecode written by `compile_code` and run it directly, skipping
+/// the source parser entirely. `text` is recognized by its header rather
+/// than `filename`'s extension, so `run` works the same whatever the file
+/// is called.
+fn run_bytecode_code(vm: &mut VM, filename: &str, text: String) -> bool {
+	match bytecode::load(vm, &text) {
+		Ok(f) => match vm.run(&f) {
+			Ok(()) => true,
+			Err(d) => {
+				eprintln!("{}: {}", filename, d.message);
+				false
+			}
+		},
+		Err(e) => {
+			eprintln!("{}: {}", filename, e);
+			false
+		}
+	}
+}
+
+fn read_file_or_exit(path: &str) -> String {
+	let mut file = File::open(path).unwrap_or_else(|_| {
+		eprintln!("Failed to open file: {}", path);
+		exit(1);
+	});
+	let mut code = String::new();
+	file.read_to_string(&mut code).unwrap();
+	code
+}
+
+// Where the REPL keeps its line history across sessions.
+fn history_path() -> PathBuf {
+	let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+	path.push("ck");
+	let _ = std::fs::create_dir_all(&path);
+	path.push("history.txt");
+	path
+}
+
+fn print_top_of_stack(vm: &VM) {
+	match vm.stack.last() {
+		Some(v) => println!("{}", display_value(v)),
+		None => println!("nil"),
+	}
 }
 
 fn run_interactive(vm: &mut VM) {
-	// Interactive mode
+	// Interactive mode: rustyline gives us arrow-key editing, Ctrl-R search
+	// and persistent history; the REPL itself drives multi-line continuation
+	// by feeding lines into the same `Parser` until it reports `Ok`.
+	let history = history_path();
+	let mut editor = rustyline::DefaultEditor::new().expect("Cannot start line editor");
+	let _ = editor.load_history(&history);
+
 	loop {
 		let mut parser = Parser::new(vm);
-		let f = loop {
-			eprint!("> ");
-			std::io::stdout().flush().unwrap();
-			let mut buf = String::new();
-			std::io::stdin().read_line(&mut buf).unwrap();
-			match parser.parse(buf.as_str()) {
-				Ok(f) => { break f; }
-				Err(None) => {
-					// Incomplete
-					eprintln!("Incomplete");
+		let mut prompt = "> ";
+		// `parsed` carries the successfully-parsed Func and the source text
+		// its spans are relative to, out of the inner loop, so `parser` (and
+		// its borrow of `vm`) can be dropped before we run it.
+		let parsed = loop {
+			match editor.readline(prompt) {
+				Ok(line) => {
+					let _ = editor.add_history_entry(line.as_str());
+					let _ = editor.save_history(&history);
+					// readline strips the newline; put it back so tokens and
+					// `#` comments don't run into the next continuation line.
+					match parser.parse(&format!("{}\n", line)) {
+						Ok(f) => break Some((f, parser.source().to_string())),
+						Err(ParseErr::Incomplete(_)) => {
+							// Keep reading into the same Parser instance
+							prompt = "... ";
+						}
+						Err(ParseErr::Error(e)) => {
+							eprintln!("{}", e.render(parser.source()));
+							break None;
+						}
+					}
+				}
+				Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+					let _ = editor.save_history(&history);
+					return;
 				}
-				Err(Some(e)) => {
-					eprintln!("{}", e);
+				Err(e) => {
+					eprintln!("Readline error: {}", e);
+					let _ = editor.save_history(&history);
+					return;
 				}
 			}
 		};
-		println!("Run: {:?}", f);
-		vm.run();
+		if let Some((f, source)) = parsed {
+			match vm.run(&f) {
+				Ok(()) => print_top_of_stack(vm),
+				Err(d) => eprintln!("{}", d.render(&source)),
+			}
+		}
 	}
 }
 
-fn run_with_args(vm: &mut VM, mut args: std::env::Args) {
-	let mut code_executed = false;
-	let mut interactive = false;
-    args.next();
-    let mut args = args.enumerate();
-    while let Some(a) = args.next() {
-        match a.1.as_ref() {
-            "-i" => interactive = true,
-            "-c" => {
-            	let (i, filename) = args.next().expect("Filename missing");
-                code_executed = true;
-                execute_code(vm, format!("<arg-{}>", i), filename);
-            }
-            "-f" => {
-           		let (i, filename) = args.next().expect("Filename missing");
-                if let Err(_) = vm.load_buffer_file(filename.as_str()) {
-                	eprintln!("Failed to load buffer file: {}", filename);
-					exit(1);
-                }
-            }
-            "-h" => print_help_and_exit(),
-            "-v" => print_version_and_exit(),
-            s => {
-                if s.starts_with("-") {
-                    eprintln!("Unknown option: {}", s);
-                    exit(1);
-                }
-                // Read file, name s
-                let mut file =
-                    File::open(s).unwrap_or_else(|_| panic!("Failed to open file: {}", s));
-                let mut code = String::new();
-                file.read_to_string(&mut code).unwrap();
-                code_executed = true;
-                execute_code(vm, s.to_string(), code);
-            }
-        }
-    }
-    if !code_executed || interactive {
-		run_interactive(vm);
+fn load_buffer_or_exit(vm: &mut VM, filename: &str) {
+	if vm.load_buffer_file(filename).is_err() {
+		eprintln!("Failed to load buffer file: {}", filename);
+		exit(1);
+	}
+}
+
+fn run_cli(vm: &mut VM, cli: Cli) {
+	match cli.command {
+		Command::Run { file, buffer } => {
+			if let Some(buffer) = buffer {
+				load_buffer_or_exit(vm, &buffer);
+			}
+			let code = read_file_or_exit(&file);
+			let ok = if bytecode::looks_like_bytecode(&code) {
+				run_bytecode_code(vm, &file, code)
+			} else {
+				execute_code(vm, file, code)
+			};
+			if !ok {
+				exit(1);
+			}
+		}
+		Command::Check { file } => {
+			let code = read_file_or_exit(&file);
+			if !check_code(vm, file, code) {
+				exit(1);
+			}
+		}
+		Command::Compile { file, output } => {
+			let code = read_file_or_exit(&file);
+			if !compile_code(vm, file, code, &output) {
+				exit(1);
+			}
+		}
+		Command::Repl { buffer } => {
+			if let Some(buffer) = buffer {
+				load_buffer_or_exit(vm, &buffer);
+			}
+			run_interactive(vm);
+		}
+		Command::Eval { code } => {
+			if !execute_code(vm, "<eval>".to_string(), code) {
+				exit(1);
+			}
+		}
 	}
 }
 
@@ -424,6 +1004,7 @@ fn main() {
     })
     .expect("Cannot setting Ctrl-C Handler");
 
+    let cli = Cli::parse();
     let mut vm = VM::new();
-    run_with_args(&mut vm, std::env::args());
+    run_cli(&mut vm, cli);
 }